@@ -1,9 +1,99 @@
-use std::{fs::File, io::Error, path::Path, process::Command, vec};
-
-use clap::Parser;
+#[cfg(not(feature = "ffmpeg"))]
+use std::process::Command;
+use std::{
+    f64::consts::PI,
+    fs::File,
+    io::Error,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+    vec,
+};
+
+use clap::{Parser, ValueEnum};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use midly::{Format, Header, Timing, TrackEvent, TrackEventKind};
 use pix::{hwb::SHwb8, Raster};
-use rustfft::{num_complex::Complex, FftPlanner};
+use realfft::{num_complex::Complex64, RealFftPlanner};
+
+/// Analysis window applied to each frame before the FFT in order to reduce
+/// spectral leakage from the frame's rectangular truncation.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum WindowFunction {
+    Rectangular,
+    Hann,
+    Hamming,
+    Blackman,
+}
+
+impl WindowFunction {
+    /// Builds the window coefficients `w[n]` for a frame of length `size`.
+    fn coefficients(&self, size: usize) -> Vec<f64> {
+        let denom = (size.max(2) - 1) as f64;
+        (0..size)
+            .map(|n| {
+                let phase = 2.0 * PI * n as f64 / denom;
+                match self {
+                    WindowFunction::Rectangular => 1.0,
+                    WindowFunction::Hann => 0.5 - 0.5 * phase.cos(),
+                    WindowFunction::Hamming => 0.54 - 0.46 * phase.cos(),
+                    WindowFunction::Blackman => {
+                        0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos()
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// Interpolation kernel used to reconstruct sample values between two source samples
+/// while resampling, selected via `--interp`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum InterpolationKind {
+    Nearest,
+    Linear,
+    Cosine,
+    Cubic,
+}
+
+impl InterpolationKind {
+    /// Interpolates a value at fractional position `t` (0.0-1.0) between `y0` and `y1`,
+    /// given the samples immediately before `y0` (`y_prev`) and after `y1` (`y_next`) for
+    /// the kernels that need neighbors beyond the immediate interval.
+    fn interpolate(&self, y_prev: f64, y0: f64, y1: f64, y_next: f64, t: f64) -> f64 {
+        match self {
+            InterpolationKind::Nearest => {
+                if t < 0.5 {
+                    y0
+                } else {
+                    y1
+                }
+            }
+            InterpolationKind::Linear => y0 + (y1 - y0) * t,
+            InterpolationKind::Cosine => y0 + (y1 - y0) * (1.0 - (PI * t).cos()) / 2.0,
+            InterpolationKind::Cubic => {
+                // Catmull-Rom spline through (y_prev, y0, y1, y_next)
+                let a0 = -0.5 * y_prev + 1.5 * y0 - 1.5 * y1 + 0.5 * y_next;
+                let a1 = y_prev - 2.5 * y0 + 2.0 * y1 - 0.5 * y_next;
+                let a2 = -0.5 * y_prev + 0.5 * y1;
+                let a3 = y0;
+                ((a0 * t + a1) * t + a2) * t + a3
+            }
+        }
+    }
+}
+
+/// Selects which time/frequency analysis `main` runs before note generation.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum TransformMode {
+    /// Linear FFT, analyzed in `fft_transform`.
+    Fft,
+    /// Constant-Q transform with geometrically-spaced, note-aligned bins, analyzed in `cqt_transform`.
+    Cqt,
+}
 
 #[derive(Parser, Debug)]
 struct ProgramArgs {
@@ -23,6 +113,33 @@ struct ProgramArgs {
     /// amount of time in seconds to process frames
     #[clap(long, short)]
     duration: Option<f64>,
+    /// window function applied to each frame before the FFT, to reduce spectral leakage
+    #[clap(long, value_enum, default_value_t = WindowFunction::Hann)]
+    window: WindowFunction,
+    /// time/frequency analysis to run: a linear `fft`, or a note-aligned `cqt`
+    #[clap(long, value_enum, default_value_t = TransformMode::Fft)]
+    transform: TransformMode,
+    /// normalized energy (0.0-1.0) above which a note turns on
+    #[clap(long, default_value_t = 0.5)]
+    t_on: f64,
+    /// normalized energy (0.0-1.0) below which an "on" note turns back off.
+    /// should be lower than `t_on` to avoid the note flickering on/off
+    #[clap(long, default_value_t = 0.2)]
+    t_off: f64,
+    /// number of MIDI ticks that a single time slice (one `fft_map` column) advances
+    #[clap(long, default_value_t = 12)]
+    ticks_per_slice: u32,
+    /// resample the decoded PCM to this rate (Hz) before analysis, so frequency
+    /// analysis behaves consistently regardless of the source file's native rate
+    #[clap(long)]
+    resample: Option<u32>,
+    /// interpolation kernel used when resampling
+    #[clap(long, value_enum, default_value_t = InterpolationKind::Linear)]
+    interp: InterpolationKind,
+    /// synthesize the detected notes and play them back on the default output device,
+    /// so the transcription can be checked by ear instead of loading the MIDI elsewhere
+    #[clap(long)]
+    play: bool,
 }
 
 impl ProgramArgs {
@@ -35,11 +152,18 @@ fn main() -> Result<(), Error> {
     let args = ProgramArgs::parse();
     println!("{args:#?}");
 
-    let wav_filepath = path_into_wav(Path::new(&args.audio_filepath)).unwrap();
+    let input_filepath = Path::new(&args.audio_filepath);
+    let (mut audio, mut pcm_samples) = decode_audio(input_filepath)?;
 
-    let (header, pcm_samples) = parse_wav(wav_filepath.to_str().unwrap())?;
+    if let Some(target_rate) = args.resample {
+        pcm_samples = resample_pcm(&pcm_samples, &audio, target_rate, args.interp);
+        audio.sampling_rate = target_rate;
+    }
 
-    let (mut fft_map, width, height) = fft_transform(&pcm_samples, &header, &args)?;
+    let (mut fft_map, width, height) = match args.transform {
+        TransformMode::Fft => fft_transform(&pcm_samples, &audio, &args)?,
+        TransformMode::Cqt => cqt_transform(&pcm_samples, &audio, &args)?,
+    };
 
     amplify_and_normalize(&mut fft_map, None);
 
@@ -47,14 +171,31 @@ fn main() -> Result<(), Error> {
         &fft_map,
         width,
         height / 10,
-        wav_filepath.with_extension("png").to_str().unwrap(),
+        input_filepath.with_extension("png").to_str().unwrap(),
     );
 
+    let notes = match args.transform {
+        TransformMode::Fft => {
+            // the same frame length `fft_transform` analyzed with; recovering it from
+            // `height = read_chunk_size / 2 + 1` would lose one for odd sizes
+            let read_chunk_size = (args.get_chunk_step() * audio.sampling_rate as f64) as usize;
+            generate_notes(&fft_map, &args, |time_slice| {
+                fft_bin_note_energy(time_slice, audio.sampling_rate, read_chunk_size)
+            })
+        }
+        TransformMode::Cqt => generate_notes(&fft_map, &args, cqt_bin_note_energy),
+    };
+
     save_midi(
-        &fft_map,
-        wav_filepath.with_extension("midi").to_str().unwrap(),
+        &notes,
+        &args,
+        input_filepath.with_extension("midi").to_str().unwrap(),
     );
 
+    if args.play {
+        play_notes(&notes, &args)?;
+    }
+
     Ok(())
 }
 
@@ -77,9 +218,162 @@ fn amplify_and_normalize(fft_map: &mut [Vec<f64>], optional_amplifier: Option<fn
         .for_each(|val| *val /= signal_max);
 }
 
+/// The handful of stream properties the rest of the pipeline needs, independent
+/// of whether the audio was decoded via the `wav` crate or in-process via ffmpeg.
+#[derive(Debug)]
+struct AudioStream {
+    sampling_rate: u32,
+    channel_count: u16,
+}
+
+/// Resamples interleaved PCM from `audio.sampling_rate` to `target_rate`, per channel.
+///
+/// Walks each output frame index `i`, maps it back to a fractional source position
+/// `i * src_rate / dst_rate`, and interpolates between the neighboring source frames
+/// with the given kernel, clamping at the buffer edges. Doing this up front means the
+/// bin-to-note frequency mapping downstream can assume a single, known sampling rate
+/// regardless of what the source file was recorded at.
+fn resample_pcm(
+    pcm_samples: &[f64],
+    audio: &AudioStream,
+    target_rate: u32,
+    interp: InterpolationKind,
+) -> Vec<f64> {
+    let channel_count = audio.channel_count as usize;
+    let frame_count = pcm_samples.len() / channel_count;
+    if frame_count == 0 || audio.sampling_rate == target_rate {
+        return pcm_samples.to_vec();
+    }
+
+    let frame_at = |frame: isize, channel: usize| -> f64 {
+        let clamped = frame.clamp(0, frame_count as isize - 1) as usize;
+        pcm_samples[clamped * channel_count + channel]
+    };
+
+    let out_frame_count =
+        (frame_count as f64 * target_rate as f64 / audio.sampling_rate as f64).round() as usize;
+    let mut resampled = Vec::with_capacity(out_frame_count * channel_count);
+
+    for i in 0..out_frame_count {
+        let source_position = i as f64 * audio.sampling_rate as f64 / target_rate as f64;
+        let frame0 = source_position.floor() as isize;
+        let t = source_position - frame0 as f64;
+
+        for channel in 0..channel_count {
+            resampled.push(interp.interpolate(
+                frame_at(frame0 - 1, channel),
+                frame_at(frame0, channel),
+                frame_at(frame0 + 1, channel),
+                frame_at(frame0 + 2, channel),
+                t,
+            ));
+        }
+    }
+
+    resampled
+}
+
+/// Decodes `filepath` into its sampling rate, channel count, and interleaved `f64` PCM samples.
+///
+/// With the `ffmpeg` feature enabled this decodes mp3/flac/ogg/m4a/etc. natively and
+/// in-process via `ffmpeg-next`, with no intermediate file written to disk. Without it,
+/// this falls back to converting the input to a WAV file beside it (left on disk afterwards)
+/// with the `ffmpeg` binary on `PATH` and parsing that with the `wav` crate, as before.
+fn decode_audio(filepath: &Path) -> Result<(AudioStream, Vec<f64>), Error> {
+    #[cfg(feature = "ffmpeg")]
+    {
+        decode_audio_ffmpeg(filepath)
+    }
+
+    #[cfg(not(feature = "ffmpeg"))]
+    {
+        let wav_filepath = path_into_wav(filepath).unwrap();
+        let (header, pcm_samples) = parse_wav(wav_filepath.to_str().unwrap())?;
+        Ok((
+            AudioStream {
+                sampling_rate: header.sampling_rate,
+                channel_count: header.channel_count,
+            },
+            pcm_samples,
+        ))
+    }
+}
+
+/// Decodes `filepath` in-process with `ffmpeg-next`: demux, find the best audio stream,
+/// decode its packets, and resample every frame to interleaved `f64` PCM via ffmpeg's
+/// software resampler. This supports any format ffmpeg's demuxers understand.
+#[cfg(feature = "ffmpeg")]
+fn decode_audio_ffmpeg(filepath: &Path) -> Result<(AudioStream, Vec<f64>), Error> {
+    fn ffmpeg_err(error: impl std::fmt::Display) -> Error {
+        Error::new(std::io::ErrorKind::Other, error.to_string())
+    }
+
+    ffmpeg_next::init().map_err(ffmpeg_err)?;
+
+    let mut input = ffmpeg_next::format::input(&filepath).map_err(ffmpeg_err)?;
+
+    let stream = input
+        .streams()
+        .best(ffmpeg_next::media::Type::Audio)
+        .ok_or_else(|| Error::new(std::io::ErrorKind::NotFound, "no audio stream found"))?;
+    let stream_index = stream.index();
+
+    let context = ffmpeg_next::codec::context::Context::from_parameters(stream.parameters())
+        .map_err(ffmpeg_err)?;
+    let mut decoder = context.decoder().audio().map_err(ffmpeg_err)?;
+
+    let sampling_rate = decoder.rate();
+    let channel_layout = decoder.channel_layout();
+
+    // resample every frame to interleaved f64 PCM at the decoder's own rate and channel
+    // layout, so downstream stages always see a consistent, self-describing sample format
+    let mut resampler = decoder
+        .resampler(
+            ffmpeg_next::format::Sample::F64(ffmpeg_next::format::sample::Type::Packed),
+            channel_layout,
+            sampling_rate,
+        )
+        .map_err(ffmpeg_err)?;
+
+    let mut pcm_samples = Vec::new();
+    let mut decoded = ffmpeg_next::frame::Audio::empty();
+    let mut resampled = ffmpeg_next::frame::Audio::empty();
+
+    let mut push_resampled_frames =
+        |decoder: &mut ffmpeg_next::decoder::Audio| -> Result<(), Error> {
+            while decoder.receive_frame(&mut decoded).is_ok() {
+                resampler
+                    .run(&decoded, &mut resampled)
+                    .map_err(ffmpeg_err)?;
+                pcm_samples.extend_from_slice(resampled.plane::<f64>(0));
+            }
+            Ok(())
+        };
+
+    for (packet_stream, packet) in input.packets() {
+        if packet_stream.index() != stream_index {
+            continue;
+        }
+        decoder.send_packet(&packet).map_err(ffmpeg_err)?;
+        push_resampled_frames(&mut decoder)?;
+    }
+
+    decoder.send_eof().map_err(ffmpeg_err)?;
+    push_resampled_frames(&mut decoder)?;
+
+    Ok((
+        AudioStream {
+            sampling_rate,
+            channel_count: channel_layout.channels() as u16,
+        },
+        pcm_samples,
+    ))
+}
+
 /// Extracts the Header and PCM data from a WAV format audio file.
 /// PCM data is converted into f64 such that the FFT becomes easier to handle
 /// overflow and normalizing operations
+#[cfg(not(feature = "ffmpeg"))]
 fn parse_wav(input_file: &str) -> Result<(wav::Header, Vec<f64>), Error> {
     let (header, data) = wav::read(&mut File::open(input_file)?)?;
 
@@ -119,30 +413,31 @@ fn parse_wav(input_file: &str) -> Result<(wav::Header, Vec<f64>), Error> {
 /// Returns the 2D matrix of FFT data, along with the dimensions of the data
 ///
 /// ## Notes
-/// - half of the FFT data per-timeslice is discarded as it is a mirror of the first half.
+/// - since the input PCM is real-valued, a real-to-complex FFT is used, which
+///   natively produces only the non-mirrored half of the spectrum (`N/2 + 1` bins).
 /// - data points are additive per channel, meaning that frequencies occuring in both channels will appear stronger
 fn fft_transform(
     pcm_samples: &[f64],
-    header: &wav::Header,
+    audio: &AudioStream,
     args: &ProgramArgs,
 ) -> Result<(Vec<Vec<f64>>, usize, usize), Error> {
     let last_frame = pcm_samples.len() - 1;
     // number of frames to skip for each column in the transform map
-    let step_size = (args.time_step * header.sampling_rate as f64) as usize;
+    let step_size = (args.time_step * audio.sampling_rate as f64) as usize;
     // Starting frame index
-    let start_chunk = ((args.start_time * header.sampling_rate as f64) as usize).min(last_frame);
+    let start_chunk = ((args.start_time * audio.sampling_rate as f64) as usize).min(last_frame);
     // Ending frame index
     let end_chunk = args.duration.map_or_else(
         || last_frame,
         |seconds| {
-            let duration_chunk = (seconds * header.sampling_rate as f64) as usize;
+            let duration_chunk = (seconds * audio.sampling_rate as f64) as usize;
             (start_chunk + duration_chunk).min(last_frame)
         },
     );
     // the amount of frames to process for each time step
-    let read_chunk_size = (args.get_chunk_step() * header.sampling_rate as f64) as usize;
+    let read_chunk_size = (args.get_chunk_step() * audio.sampling_rate as f64) as usize;
 
-    println!("{header:#?}");
+    println!("{audio:#?}");
     println!("sample_count:     {}", pcm_samples.len());
     println!("chunk_size:       {}", step_size);
     println!("start_chunk:      {}", start_chunk);
@@ -152,45 +447,54 @@ fn fft_transform(
     // dimensions of the resulting fft_map
     // (the extra + 1 is necessary for numbers that dont divide nicely?)
     let width = (end_chunk - start_chunk) / step_size + 1;
-    // this removes the mirror frequencies on the higher range,
-    // which is caused by the symmetry of the real portion of the Fast Fourier Transform
-    let height = read_chunk_size / 2;
+    // a real-to-complex FFT of a length-N input produces exactly N/2 + 1 complex bins
+    let height = read_chunk_size / 2 + 1;
 
     // Vec to store FFT transformation after the buffer is overwritten
     let mut fft_map = vec![vec![0_f64; height]; width];
 
     // Setup FFT transformer
-    let fft = FftPlanner::new().plan_fft_forward(read_chunk_size);
+    let r2c = RealFftPlanner::<f64>::new().plan_fft_forward(read_chunk_size);
+    let mut indata = r2c.make_input_vec();
+    let mut spectrum = r2c.make_output_vec();
+
+    // Precompute the analysis window once, since every frame is the same length.
+    // Windowing attenuates the signal at the frame edges, so we divide each
+    // magnitude by the window's coherent gain (its mean value) to keep
+    // magnitudes comparable across window choices.
+    let window = args.window.coefficients(read_chunk_size);
+    let coherent_gain = window.iter().sum::<f64>() / read_chunk_size as f64;
 
     for (x, chunk_start) in (start_chunk..end_chunk).step_by(step_size).enumerate() {
         // reading the data for a single channel at a time and performing FFT on it
-        for channel in 0..header.channel_count as usize {
-            let buffer = &mut pcm_samples[channel
+        for channel in 0..audio.channel_count as usize {
+            // make sure that there is padding on the buffer,
+            // because the FFT expects a buffer of fixed length
+            // !!! This behavior is still not right because it needs to pad left or right depending on if it is at the beginning or end of the file
+            indata.fill(0.0);
+
+            for (sample, (w, slot)) in pcm_samples[channel
                 + chunk_start
                     .saturating_add(read_chunk_size / 2)
                     .max(0)
                     .min(last_frame)..]
                 .iter()
-                .step_by(header.channel_count as usize)
+                .step_by(audio.channel_count as usize)
                 .take(read_chunk_size)
-                // turn the numbers into Complex form for fft library
-                .map(Complex::from)
-                .collect::<Vec<Complex<f64>>>();
-
-            // make sure that there is padding on the buffer,
-            // because the FFT expects a buffer of fixed length
-            // !!! This behavior is still not right because it needs to pad left or right depending on if it is at the beginning or end of the file
-            buffer.resize(read_chunk_size, Complex::from(0_f64));
+                .zip(window.iter().zip(indata.iter_mut()))
+            {
+                *slot = sample * w;
+            }
 
             // FFT step
-            fft.process(buffer);
+            r2c.process(&mut indata, &mut spectrum)
+                .expect("real FFT input/output buffers did not match the plan's length");
 
-            for (y, magnitude) in buffer
+            for (y, magnitude) in spectrum
                 .iter()
-                .take(height)
                 // ignore the DC component by skipping the 0th index (corresponding to no period)
                 .skip(1)
-                .map(|complex| complex.re.abs())
+                .map(|complex| complex.norm() / coherent_gain)
                 .enumerate()
             {
                 fft_map[x][y] += magnitude;
@@ -201,6 +505,105 @@ fn fft_transform(
     Ok((fft_map, width, height))
 }
 
+/// Lowest analyzed frequency for the Constant-Q transform: A0, the bottom key of a piano.
+const CQT_MIN_FREQUENCY: f64 = 27.5;
+/// Bins per octave; one bin per semitone gives a fully chromatic, note-aligned spectrum.
+const CQT_BINS_PER_OCTAVE: usize = 12;
+/// Number of CQT bins, spanning A0 to C8 (the 88 keys of a standard piano).
+const CQT_BIN_COUNT: usize = 88;
+/// MIDI note number of the CQT's first bin (A0).
+const CQT_MIN_MIDI_NOTE: usize = 21;
+
+/// Returns a Constant-Q transform of `pcm_samples`, whose rows already correspond 1:1 to
+/// the piano's 88 semitones (row `k` is the note `CQT_MIN_MIDI_NOTE + k`), using geometrically
+/// spaced center frequencies `f_k = CQT_MIN_FREQUENCY * 2^(k / CQT_BINS_PER_OCTAVE)`. Unlike
+/// the linear FFT, each bin is evaluated with its own window length `N_k`, so low-frequency
+/// bins get the long window they need for semitone-level resolution while high-frequency bins
+/// stay short, matching the ear's musically-scaled frequency resolution.
+///
+/// ## Notes
+/// - data points are additive per channel, meaning that frequencies occuring in both channels will appear stronger
+fn cqt_transform(
+    pcm_samples: &[f64],
+    audio: &AudioStream,
+    args: &ProgramArgs,
+) -> Result<(Vec<Vec<f64>>, usize, usize), Error> {
+    let last_frame = pcm_samples.len() - 1;
+    let step_size = (args.time_step * audio.sampling_rate as f64) as usize;
+    let start_chunk = ((args.start_time * audio.sampling_rate as f64) as usize).min(last_frame);
+    let end_chunk = args.duration.map_or_else(
+        || last_frame,
+        |seconds| {
+            let duration_chunk = (seconds * audio.sampling_rate as f64) as usize;
+            (start_chunk + duration_chunk).min(last_frame)
+        },
+    );
+
+    let width = (end_chunk - start_chunk) / step_size + 1;
+    let height = CQT_BIN_COUNT;
+
+    // quality factor shared by every bin, since bins are geometrically spaced by a fixed ratio
+    let quality_factor = 1.0 / (2_f64.powf(1.0 / CQT_BINS_PER_OCTAVE as f64) - 1.0);
+
+    // precompute each bin's window and per-sample kernel phase step once, since every
+    // time slice re-uses the same bank of analysis kernels
+    let kernels: Vec<(Vec<f64>, f64)> = (0..CQT_BIN_COUNT)
+        .map(|k| {
+            let center_frequency =
+                CQT_MIN_FREQUENCY * 2_f64.powf(k as f64 / CQT_BINS_PER_OCTAVE as f64);
+            let window_len = (quality_factor * audio.sampling_rate as f64 / center_frequency)
+                .round()
+                .max(1.0) as usize;
+            let phase_step = 2.0 * PI * quality_factor / window_len as f64;
+            (WindowFunction::Hann.coefficients(window_len), phase_step)
+        })
+        .collect();
+
+    // each time slice evaluates every bin's own direct DFT kernel (O(width * sum(N_k)),
+    // with low-octave N_k in the tens of thousands), which is far slower per-slice than
+    // the shared-FFT `fft_transform` path -- a long run here is expected, not a hang
+    let total_kernel_length: usize = kernels.iter().map(|(window, _)| window.len()).sum();
+    println!(
+        "cqt_transform: {width} slices x {height} bins, {total_kernel_length} kernel samples/slice"
+    );
+
+    let mut fft_map = vec![vec![0_f64; height]; width];
+
+    for (x, chunk_start) in (start_chunk..end_chunk).step_by(step_size).enumerate() {
+        for channel in 0..audio.channel_count as usize {
+            for (y, (window, phase_step)) in kernels.iter().enumerate() {
+                let window_len = window.len();
+                // center this bin's window on `chunk_start`, same as every other bin, so
+                // bins with very different window lengths still analyze the same instant
+                // (the window spans `window_len` interleaved frames of `channel_count` samples each)
+                let offset = channel
+                    + chunk_start
+                        .saturating_sub(window_len / 2 * audio.channel_count as usize)
+                        .min(last_frame);
+
+                let mut kernel_sum = Complex64::new(0.0, 0.0);
+                for (n, (&w, &sample)) in window
+                    .iter()
+                    .zip(
+                        pcm_samples[offset..]
+                            .iter()
+                            .step_by(audio.channel_count as usize)
+                            .take(window_len),
+                    )
+                    .enumerate()
+                {
+                    let phase = -phase_step * n as f64;
+                    kernel_sum += w * sample * Complex64::new(phase.cos(), phase.sin());
+                }
+
+                fft_map[x][y] += kernel_sum.norm() / window_len as f64;
+            }
+        }
+    }
+
+    Ok((fft_map, width, height))
+}
+
 /// Renders the FFT values onto a Raster image so that you can visualize the resulting frequencies
 /// This is the precursor to a MIDI translation of the data, as the image is easy to understand
 /// but not concrete enough to make inferences about the notes/keys being concurrently pressed
@@ -238,31 +641,182 @@ fn save_image(fft_map: &[Vec<f64>], width: usize, height: usize, image_file: &st
     .expect("failed to parse raster to image.");
 }
 
-/// Save the fft_data into a MIDI format
+/// Number of MIDI note numbers, covering the full 10-octave MIDI range (C-1 to G9).
+const MIDI_NOTE_COUNT: usize = 128;
+
+/// A detected note with the time slices it was held across and its onset velocity.
+struct NoteEvent {
+    note: u8,
+    start_slice: usize,
+    end_slice: usize,
+    velocity: u8,
+}
+
+/// Maps one `fft_transform` time slice onto per-note energies.
+///
+/// For each FFT bin `k` (the bin's index in the original, un-truncated spectrum,
+/// recovering the DC bin that `fft_transform` skips), the corresponding frequency
+/// is `f = k * sampling_rate / read_chunk_size`, which maps onto MIDI note number
+/// `m = round(69 + 12 * log2(f / 440))`. The magnitudes of every bin falling into
+/// the same note are summed.
+fn fft_bin_note_energy(
+    time_slice: &[f64],
+    sampling_rate: u32,
+    read_chunk_size: usize,
+) -> [f64; MIDI_NOTE_COUNT] {
+    let mut note_energy = [0_f64; MIDI_NOTE_COUNT];
+
+    for (y, magnitude) in time_slice.iter().enumerate() {
+        // `y` is the index after `fft_transform` skips the DC bin, so the
+        // original FFT bin index is `y + 1`
+        let bin = y + 1;
+        let frequency = bin as f64 * sampling_rate as f64 / read_chunk_size as f64;
+        if frequency <= 0.0 {
+            continue;
+        }
+
+        let note = (69.0 + 12.0 * (frequency / 440.0).log2()).round();
+        if note < 0.0 || note >= MIDI_NOTE_COUNT as f64 {
+            continue;
+        }
+
+        note_energy[note as usize] += magnitude;
+    }
+
+    note_energy
+}
+
+/// Maps one `cqt_transform` time slice onto per-note energies.
+///
+/// The CQT's bins already correspond 1:1 to semitones, so this is a direct
+/// re-index from CQT bin `k` to MIDI note `CQT_MIN_MIDI_NOTE + k` with no binning.
+fn cqt_bin_note_energy(time_slice: &[f64]) -> [f64; MIDI_NOTE_COUNT] {
+    let mut note_energy = [0_f64; MIDI_NOTE_COUNT];
+
+    for (y, &magnitude) in time_slice.iter().enumerate() {
+        if let Some(note) = CQT_MIN_MIDI_NOTE
+            .checked_add(y)
+            .filter(|n| *n < MIDI_NOTE_COUNT)
+        {
+            note_energy[note] = magnitude;
+        }
+    }
+
+    note_energy
+}
+
+/// Turns per-slice note energies into note events using hysteresis thresholding.
+///
+/// `note_energy` maps a raw time slice (one `fft_map` column) onto the energy present
+/// in each of the 128 MIDI notes; `fft_bin_note_energy` and `cqt_bin_note_energy` provide
+/// this for the FFT and CQT transforms respectively. Energies are normalized by each
+/// slice's peak before thresholding, so `t_on`/`t_off` are relative, not absolute.
+///
+/// A note turns on once its normalized energy exceeds `args.t_on`, and stays on
+/// until it drops below the lower `args.t_off` threshold; this hysteresis gap
+/// prevents a note that hovers near one threshold from flickering on and off.
+fn generate_notes(
+    fft_map: &[Vec<f64>],
+    args: &ProgramArgs,
+    note_energy: impl Fn(&[f64]) -> [f64; MIDI_NOTE_COUNT],
+) -> Vec<NoteEvent> {
+    let mut notes = vec![];
+    // slice index at which each note last turned on, if it is currently on
+    let mut note_on_since: [Option<(usize, u8)>; MIDI_NOTE_COUNT] = [None; MIDI_NOTE_COUNT];
+
+    for (x, time_slice) in fft_map.iter().enumerate() {
+        let mut note_energy = note_energy(time_slice);
+
+        let slice_max = note_energy.iter().fold(0_f64, |max, e| e.max(max));
+        if slice_max > 0.0 {
+            note_energy.iter_mut().for_each(|e| *e /= slice_max);
+        }
+
+        for (note, &energy) in note_energy.iter().enumerate() {
+            match note_on_since[note] {
+                Some(_) if energy < args.t_off => {
+                    let (start_slice, velocity) = note_on_since[note].take().unwrap();
+                    notes.push(NoteEvent {
+                        note: note as u8,
+                        start_slice,
+                        end_slice: x,
+                        velocity,
+                    });
+                }
+                None if energy > args.t_on => {
+                    let velocity = (1.0 + energy.min(1.0) * 126.0).round() as u8;
+                    note_on_since[note] = Some((x, velocity));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // close out any notes that were still held at the end of the data
+    let last_slice = fft_map.len();
+    for (note, on_since) in note_on_since.into_iter().enumerate() {
+        if let Some((start_slice, velocity)) = on_since {
+            notes.push(NoteEvent {
+                note: note as u8,
+                start_slice,
+                end_slice: last_slice,
+                velocity,
+            });
+        }
+    }
+
+    notes
+}
+
+/// Save a set of note events into a MIDI format
 ///
 /// ## Operations
-/// 1. interpret FFT data as notes with start & stop times
-/// 2. sorts objects by time to be used as delta in MIDI standard
-/// 3. parses list into TrackEvents
-fn save_midi(fft_data: &[Vec<f64>], file_path: &str) {
+/// 1. converts each note's start & end slice into a NoteOn/NoteOff pair of TrackEvents
+/// 2. sorts events by absolute time to compute deltas per the MIDI standard
+/// 3. writes the resulting track with `midly::write_std`
+fn save_midi(notes: &[NoteEvent], args: &ProgramArgs, file_path: &str) {
+    // (absolute tick, event) pairs, so NoteOn/NoteOff can be sorted together before
+    // being converted into the delta-encoded ticks the MIDI format requires
+    let mut timed_events: Vec<(u32, TrackEventKind)> = notes
+        .iter()
+        .flat_map(|note| {
+            let on_tick = note.start_slice as u32 * args.ticks_per_slice;
+            let off_tick = note.end_slice as u32 * args.ticks_per_slice;
+            [
+                (
+                    on_tick,
+                    TrackEventKind::Midi {
+                        channel: 0.into(),
+                        message: midly::MidiMessage::NoteOn {
+                            key: note.note.into(),
+                            vel: note.velocity.into(),
+                        },
+                    },
+                ),
+                (
+                    off_tick,
+                    TrackEventKind::Midi {
+                        channel: 0.into(),
+                        message: midly::MidiMessage::NoteOff {
+                            key: note.note.into(),
+                            vel: 0.into(),
+                        },
+                    },
+                ),
+            ]
+        })
+        .collect();
+    timed_events.sort_by_key(|(tick, _)| *tick);
+
     let mut midi_data: Vec<TrackEvent> = vec![];
-    // generate_notes(fft_data)
-    //     .into_iter()
-    //     .map(|a| TrackEvent {
-    //         delta: (time as u32).into(),
-    //         kind: TrackEventKind::Midi {
-    //             channel: 0.into(),
-    //             message: midly::MidiMessage::NoteOn {
-    //                 vel: match order {
-    //                     0 => 90.into(),
-    //                     1 => 0.into(),
-    //                     _ => 0.into(),
-    //                 },
-    //                 key: note.into(),
-    //             },
-    //         },
-    //     })
-    //     .collect();
+    let mut previous_tick = 0_u32;
+    for (tick, kind) in timed_events {
+        midi_data.push(TrackEvent {
+            delta: (tick - previous_tick).into(),
+            kind,
+        });
+        previous_tick = tick;
+    }
 
     // append the end of track message
     midi_data.push(TrackEvent {
@@ -278,27 +832,115 @@ fn save_midi(fft_data: &[Vec<f64>], file_path: &str) {
     .expect("failed to write midi.");
 }
 
-/// Returns a set of notes that contain the start and end time
+/// Sample rate used to render the `--play` preview, independent of the source file's rate.
+const PLAYBACK_SAMPLE_RATE: u32 = 44_100;
+/// Attack/release ramp applied to each synthesized note, to avoid clicks at its on/off boundaries.
+const PLAYBACK_ENVELOPE_SECONDS: f64 = 0.01;
+
+/// Renders `notes` into a mono PCM buffer at `PLAYBACK_SAMPLE_RATE` and streams it to the
+/// default output device via `cpal`, so a transcription can be checked by ear.
 ///
-/// maybe this is a neural network
-fn generate_notes(fft_data: &[Vec<f64>]) -> Vec<u128> {
-    let mut notes = vec![];
+/// Each note is synthesized as a sine oscillator at its fundamental frequency
+/// `440 * 2^((m - 69) / 12)`, with velocity mapped to amplitude and a short linear
+/// attack/release envelope to avoid clicks, then additively mixed into the buffer.
+fn play_notes(notes: &[NoteEvent], args: &ProgramArgs) -> Result<(), Error> {
+    let buffer = synthesize_notes(notes, args.time_step, PLAYBACK_SAMPLE_RATE);
+    play_buffer(buffer, PLAYBACK_SAMPLE_RATE)
+}
 
-    for time_slice in fft_data {
-        // convolute time_slice of height of X into 88
-        // compute note vector from previous timeslice + new data
-        let note_vec = vec![1_f64; 88];
+/// Synthesizes `notes` into a mono PCM buffer, where `slice_duration` is the real-world
+/// number of seconds a single time slice (one `fft_map` column) spans.
+fn synthesize_notes(notes: &[NoteEvent], slice_duration: f64, sample_rate: u32) -> Vec<f32> {
+    let total_seconds = notes
+        .iter()
+        .map(|note| note.end_slice as f64 * slice_duration)
+        .fold(0.0, f64::max);
+    let mut buffer = vec![0_f32; (total_seconds * sample_rate as f64).ceil() as usize];
+
+    for note in notes {
+        let frequency = 440.0 * 2_f64.powf((note.note as f64 - 69.0) / 12.0);
+        let amplitude = note.velocity as f64 / 127.0;
+
+        let start_sample = (note.start_slice as f64 * slice_duration * sample_rate as f64) as usize;
+        let end_sample = (note.end_slice as f64 * slice_duration * sample_rate as f64) as usize;
+        let note_samples = end_sample.saturating_sub(start_sample).max(1);
+        let envelope_samples = ((PLAYBACK_ENVELOPE_SECONDS * sample_rate as f64) as usize)
+            .min(note_samples / 2)
+            .max(1);
+
+        for i in 0..note_samples {
+            let Some(sample) = buffer.get_mut(start_sample + i) else {
+                break;
+            };
+
+            let envelope = if i < envelope_samples {
+                (i + 1) as f64 / envelope_samples as f64
+            } else if note_samples - i <= envelope_samples {
+                (note_samples - i) as f64 / envelope_samples as f64
+            } else {
+                1.0
+            };
+
+            let phase = 2.0 * PI * frequency * (i as f64 / sample_rate as f64);
+            *sample += (amplitude * envelope * phase.sin()) as f32;
+        }
+    }
 
-        let note_bitmap = note_vec
-            .into_iter()
-            .enumerate()
-            .filter(|(_, note)| *note > 0.5)
-            .fold(0_u128, |note_bitmap, (i, _)| note_bitmap | 1 << i);
+    // overlapping notes (chords) are additively mixed and can exceed [-1.0, 1.0],
+    // so bring the whole buffer back under the clipping point by its own peak
+    let peak = buffer
+        .iter()
+        .fold(0_f32, |peak, &sample| sample.abs().max(peak));
+    if peak > 1.0 {
+        buffer.iter_mut().for_each(|sample| *sample /= peak);
+    }
+
+    buffer
+}
 
-        notes.push(note_bitmap);
+/// Streams a pre-rendered mono PCM `buffer` to the default output device, duplicating
+/// each sample across every output channel, and blocks until playback completes.
+fn play_buffer(buffer: Vec<f32>, sample_rate: u32) -> Result<(), Error> {
+    fn cpal_err(error: impl std::fmt::Display) -> Error {
+        Error::new(std::io::ErrorKind::Other, error.to_string())
     }
 
-    notes
+    let device = cpal::default_host()
+        .default_output_device()
+        .ok_or_else(|| Error::new(std::io::ErrorKind::NotFound, "no default output device"))?;
+    let config = cpal::StreamConfig {
+        channels: device.default_output_config().map_err(cpal_err)?.channels(),
+        sample_rate: cpal::SampleRate(sample_rate),
+        buffer_size: cpal::BufferSize::Default,
+    };
+    let channels = config.channels as usize;
+
+    let buffer = Arc::new(buffer);
+    let position = Arc::new(AtomicUsize::new(0));
+    let (stream_buffer, stream_position) = (buffer.clone(), position.clone());
+
+    let stream = device
+        .build_output_stream(
+            &config,
+            move |data: &mut [f32], _| {
+                for frame in data.chunks_mut(channels) {
+                    let sample_index = stream_position.fetch_add(1, Ordering::Relaxed);
+                    let sample = stream_buffer.get(sample_index).copied().unwrap_or(0.0);
+                    frame.iter_mut().for_each(|out| *out = sample);
+                }
+            },
+            |err| eprintln!("playback stream error: {err}"),
+            None,
+        )
+        .map_err(cpal_err)?;
+    stream.play().map_err(cpal_err)?;
+
+    // block until the rendered buffer has fully played out
+    while position.load(Ordering::Relaxed) < buffer.len() {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    Ok(())
 }
 
 /// Convert a provided path string for an audio file into a Path struct.
@@ -309,6 +951,7 @@ fn generate_notes(fft_data: &[Vec<f64>]) -> Vec<u128> {
 /// ## Errors
 /// - This will only work for files that are still in some valid auido format, such as mp3.
 /// - None will be returned when the file does not have an extension.
+#[cfg(not(feature = "ffmpeg"))]
 fn path_into_wav(filepath: &Path) -> Option<&Path> {
     let wav_extension_file = Box::leak(Box::new(filepath.with_extension("wav")));
 